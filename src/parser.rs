@@ -1,208 +1,664 @@
-use core::panic;
-use std::collections::{VecDeque};
+use std::collections::VecDeque;
 
 use crate::lexer::{LexerToken};
 use crate::value::Value;
 use crate::expr::{AstExpr};
+use crate::span::{Span, Spanned};
 
 pub struct Parser {
-    input: VecDeque<LexerToken>,
+    input: VecDeque<Spanned<LexerToken>>,
+    expected: Vec<Expected>,
+    label_counter: usize,
+    last_span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ParserToken {
-    DeclareVariable(String), // Pops a value from stack and stores it to stack
+    DeclareVariable(String, Span), // Pops a value from stack and stores it to stack
     DeclareFunction(String, Vec<ParserToken>),
-    GetVariable(String),     // Pushes the variables value to stack
-    Operation(String),       // Pops 2 values from stack as arguments and pushes a result
+    GetVariable(String, Span),     // Pushes the variables value to stack
+    Operation(String, Span),       // Pops 2 values from stack as arguments and pushes a result
     Push(Value),
     Pop(),
-    Call(String, u8),        // Second argument for amount of arguments
+    Call(String, u8, Span),        // Second argument for amount of arguments
     Ret(),
+    JumpIfFalse(usize),      // Pops a value, jumps to the instruction index if it's falsy
+    Jump(usize),             // Unconditionally jumps to the instruction index
+    Label(usize),            // Marker resolved to an instruction index by `backpatch`, never reaches the stack machine
+    DeclareStruct(String, Vec<String>), // Struct name and its field names, in declared order
+    ConstructStruct(String, u8),        // Pops that many field values and pushes the constructed struct
+    GetField(String),                   // Pops a struct value and pushes the named field
+}
+
+/**
+ * A single thing the parser would have accepted at some point. Most checks look for one
+ * concrete token, but some (a variable name, a struct field name) accept any identifier, which
+ * can't be named as a `LexerToken` without already knowing it - `Identifier` covers that case
+ * instead of the error silently dropping it.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expected {
+    Token(LexerToken),
+    Identifier,
+}
+
+/**
+ * Returned by every fallible step of the `Parser` instead of panicking. `expected` on the
+ * token-shaped variants is filled in from `Parser::expected`, which accumulates every token
+ * the parser checked for since the last successful `eat`, in the order it was checked, so a
+ * failed alternation (e.g. "," or ")") reports all of the tokens that would have been accepted.
+ * `span` points at the offending token (or at the last consumed one, for EOF) so callers
+ * can render a caret under it.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    UnexpectedToken { expected: Vec<Expected>, found: LexerToken, span: Span },
+    UnexpectedEof { expected: Vec<Expected>, span: Span },
+    UnimplementedKeyword(String, Span),
 }
 
 impl Parser {
-    pub fn parse(tokens: VecDeque<LexerToken>) -> Vec<ParserToken> {
+    pub fn parse(tokens: VecDeque<Spanned<LexerToken>>) -> Result<Vec<ParserToken>, ParseError> {
         let mut parser = Parser::new(tokens);
-        parser.parse_until(LexerToken::Eof())
+        let tokens = parser.parse_until(LexerToken::Eof())?;
+        Ok(Parser::backpatch(tokens))
     }
 
     /**
      * Doesn't not append to tokens, like parse does.
      */
-    fn parse_until(&mut self, tk: LexerToken) -> Vec<ParserToken> {
+    fn parse_until(&mut self, tk: LexerToken) -> Result<Vec<ParserToken>, ParseError> {
         let mut tokens = vec![];
         'parse_loop : loop {
-            let token = self.peek().expect("Unexpted end of file");
-            if token == &tk {
+            if self.peek_is(&tk) {
                 break 'parse_loop;
             }
-            
-            if let LexerToken::Keyword(kw) = token {
+            let token = match self.peek() {
+                Some(token) => token.clone(),
+                None => return Err(ParseError::UnexpectedEof { expected: self.expected_vec(), span: self.last_span }),
+            };
+
+            if let LexerToken::Keyword(kw) = &token {
                 match kw.as_str() {
-                    "let" => { 
-                        tokens.append(&mut self.variable_decleration());
+                    "let" => {
+                        tokens.append(&mut self.variable_decleration()?);
+                    },
+                    "fn" => {
+                        tokens.append(&mut self.function_decleration()?);
+                    },
+                    "if" => {
+                        tokens.append(&mut self.if_statement()?);
+                    },
+                    "while" => {
+                        tokens.append(&mut self.while_statement()?);
                     },
-                    "fn" => { 
-                        tokens.append(&mut self.function_decleration());
+                    "return" => {
+                        tokens.append(&mut self.return_statement()?);
                     },
-                    _ => { panic!("Unimplumented keyword {}", kw); }
+                    "struct" => {
+                        tokens.append(&mut self.struct_decleration()?);
+                    },
+                    _ => { return Err(ParseError::UnimplementedKeyword(kw.clone(), self.peek_span())); }
                 }
             }
             else if let LexerToken::Identifier(fn_name) = token.clone() {
+                let fn_span = self.peek_span();
                 self.eat(); // Identifier
-                let next = self.eat().expect("Syntax error");
+                self.track_expected(Expected::Token(LexerToken::Operator("(".to_string())));
+                self.track_expected(Expected::Token(LexerToken::Symbol('{')));
+                self.track_expected(Expected::Token(LexerToken::Symbol('.')));
+                let next = self.eat().ok_or(ParseError::UnexpectedEof { expected: self.expected_vec(), span: self.last_span })?;
                 if LexerToken::Operator("(".to_string()) == next {
-                    tokens.append(&mut self.function_call(fn_name.clone()));
+                    tokens.append(&mut self.function_call(fn_name.clone(), fn_span)?);
+                    self.eat_expect(LexerToken::Symbol(';'))?;
+                }
+                else if LexerToken::Symbol('{') == next {
+                    tokens.append(&mut self.construct_struct(fn_name.clone())?);
+                    self.eat_expect(LexerToken::Symbol(';'))?;
+                }
+                else if LexerToken::Symbol('.') == next {
+                    tokens.append(&mut self.field_access(fn_name.clone(), fn_span)?);
+                    self.eat_expect(LexerToken::Symbol(';'))?;
                 }
             }
-            else 
+            else
             {
-                let mut expr = self.eat_expr(vec![LexerToken::Symbol(';')]);
-                return AstExpr::evaluate(&mut expr);
+                let mut expr = self.eat_expr(vec![LexerToken::Symbol(';')])?;
+                return Ok(AstExpr::evaluate(&mut expr));
             }
         }
-        tokens
+        Ok(tokens)
     }
 
-    #[must_use]
-    fn variable_decleration(&mut self) -> Vec<ParserToken> {
+    fn variable_decleration(&mut self) -> Result<Vec<ParserToken>, ParseError> {
         let mut tokens = vec![];
 
         // eat "let" keyword
         self.eat();
 
         // identifier
-        let tk_identifier = self.eat().expect("expected an identifier after 'let' keyword");
+        self.track_expected(Expected::Identifier);
+        let expected = self.expected_vec();
+        let tk_identifier = self.eat().ok_or(ParseError::UnexpectedEof { expected: self.expected_vec(), span: self.last_span })?;
+        let id_span = self.last_span;
         if let LexerToken::Identifier(identifier) = tk_identifier {
             // symbol '='
-            self.eat_expect(LexerToken::Symbol('='));
-            
+            self.eat_expect(LexerToken::Symbol('='))?;
+
             // Get expression
-            let mut expr = self.eat_expr(vec![LexerToken::Symbol(';')]);
-            let mut evaluated = AstExpr::evaluate(&mut expr);
+            let mut evaluated = self.parse_expr(vec![LexerToken::Symbol(';')])?;
 
             // symbol ';'
-            self.eat_expect(LexerToken::Symbol(';'));
+            self.eat_expect(LexerToken::Symbol(';'))?;
 
             // push ParserTokens
             tokens.append(&mut evaluated);
-            tokens.push(ParserToken::DeclareVariable(identifier.clone()));
-            return tokens;
+            tokens.push(ParserToken::DeclareVariable(identifier.clone(), id_span));
+            return Ok(tokens);
         }
-        panic!("expected an identifier after 'let' keyword");
+        Err(ParseError::UnexpectedToken { expected, found: tk_identifier, span: id_span })
     }
 
-    #[must_use]
-    fn function_decleration(&mut self) -> Vec<ParserToken> {
+    fn function_decleration(&mut self) -> Result<Vec<ParserToken>, ParseError> {
         // eat "fn" keyword
         self.eat();
 
         // identifier
-        let tk_identifier = self.eat().expect("expected an identifier after 'fn' keyword");
+        self.track_expected(Expected::Identifier);
+        let expected = self.expected_vec();
+        let tk_identifier = self.eat().ok_or(ParseError::UnexpectedEof { expected: self.expected_vec(), span: self.last_span })?;
         if let LexerToken::Identifier(fn_name) = tk_identifier {
             // eat operator '('
-            self.eat_expect(LexerToken::Operator("(".to_string()));
+            self.eat_expect(LexerToken::Operator("(".to_string()))?;
 
             // get argument names
             let mut fn_args: Vec<String> = vec![];
             'args : loop {
-                let tk = self.eat().expect("Invalid function decleration");
+                self.track_expected(Expected::Identifier);
+                self.track_expected(Expected::Token(LexerToken::Operator(")".to_string())));
+                let expected = self.expected_vec();
+                let tk = self.eat().ok_or(ParseError::UnexpectedEof { expected: self.expected_vec(), span: self.last_span })?;
 
                 if let LexerToken::Identifier(arg_identifier) = tk {
                     fn_args.push(arg_identifier);
-                    let peek = self.eat().expect("Invalid function decleration");
+                    self.track_expected(Expected::Token(LexerToken::Symbol(',')));
+                    self.track_expected(Expected::Token(LexerToken::Operator(")".to_string())));
+                    let expected = self.expected_vec();
+                    let peek = self.eat().ok_or(ParseError::UnexpectedEof { expected: self.expected_vec(), span: self.last_span })?;
                     if peek == LexerToken::Symbol(',') {
                         continue;
                     }
                     else if peek == LexerToken::Operator(")".to_string()) {
                         break 'args;
                     }
-                    panic!("Syntax error");
+                    return Err(ParseError::UnexpectedToken { expected, found: peek, span: self.last_span });
                 }
                 else if tk == LexerToken::Operator(")".to_string()) {
                     break 'args;
                 }
                 else {
-                    panic!("Syntax error");
+                    return Err(ParseError::UnexpectedToken { expected, found: tk, span: self.last_span });
                 }
             }
-            self.eat_expect(LexerToken::Symbol('{'));
+            self.eat_expect(LexerToken::Symbol('{'))?;
+
+            let mut fn_body = self.parse_until(LexerToken::Symbol('}'))?;
+            self.eat_expect(LexerToken::Symbol('}'))?;
 
-            let fn_body = self.parse_until(LexerToken::Symbol('}'));
-            self.eat_expect(LexerToken::Symbol('}'));
+            // A function body always needs a clean epilogue for the stack machine, even if the
+            // source never wrote an explicit "return;".
+            if fn_body.last() != Some(&ParserToken::Ret()) {
+                fn_body.push(ParserToken::Ret());
+            }
 
             // push tokens
-            return vec![(ParserToken::DeclareFunction(fn_name, fn_body))];
+            return Ok(vec![(ParserToken::DeclareFunction(fn_name, Parser::backpatch(fn_body)))]);
+        }
+        Err(ParseError::UnexpectedToken { expected, found: tk_identifier, span: self.last_span })
+    }
+
+    /**
+     * "if (cond) { ... }" with an optional "else { ... }". The condition is evaluated onto the
+     * stack and a `JumpIfFalse` skips the "then" body to the else block (or past the whole
+     * statement if there's no else), which in turn ends with a `Jump` past the else block.
+     */
+    fn if_statement(&mut self) -> Result<Vec<ParserToken>, ParseError> {
+        let mut tokens = vec![];
+
+        // eat "if" keyword
+        self.eat();
+
+        self.eat_expect(LexerToken::Operator("(".to_string()))?;
+        tokens.append(&mut self.parse_expr(vec![LexerToken::Operator(")".to_string())])?);
+        self.eat_expect(LexerToken::Operator(")".to_string()))?;
+
+        let else_label = self.next_label();
+        tokens.push(ParserToken::JumpIfFalse(else_label));
+
+        self.eat_expect(LexerToken::Symbol('{'))?;
+        tokens.append(&mut self.parse_until(LexerToken::Symbol('}'))?);
+        self.eat_expect(LexerToken::Symbol('}'))?;
+
+        if self.peek_is_keyword("else") {
+            self.eat(); // "else"
+
+            let end_label = self.next_label();
+            tokens.push(ParserToken::Jump(end_label));
+            tokens.push(ParserToken::Label(else_label));
+
+            self.eat_expect(LexerToken::Symbol('{'))?;
+            tokens.append(&mut self.parse_until(LexerToken::Symbol('}'))?);
+            self.eat_expect(LexerToken::Symbol('}'))?;
+
+            tokens.push(ParserToken::Label(end_label));
+        }
+        else {
+            tokens.push(ParserToken::Label(else_label));
+        }
+
+        Ok(tokens)
+    }
+
+    /**
+     * "while (cond) { ... }". The condition is re-evaluated on every iteration: a start label
+     * marks where the `Jump` at the end of the body loops back to, and `JumpIfFalse` exits to
+     * the end label once the condition goes falsy.
+     */
+    fn while_statement(&mut self) -> Result<Vec<ParserToken>, ParseError> {
+        let mut tokens = vec![];
+
+        // eat "while" keyword
+        self.eat();
+
+        let start_label = self.next_label();
+        let end_label = self.next_label();
+        tokens.push(ParserToken::Label(start_label));
+
+        self.eat_expect(LexerToken::Operator("(".to_string()))?;
+        tokens.append(&mut self.parse_expr(vec![LexerToken::Operator(")".to_string())])?);
+        self.eat_expect(LexerToken::Operator(")".to_string()))?;
+
+        tokens.push(ParserToken::JumpIfFalse(end_label));
+
+        self.eat_expect(LexerToken::Symbol('{'))?;
+        tokens.append(&mut self.parse_until(LexerToken::Symbol('}'))?);
+        self.eat_expect(LexerToken::Symbol('}'))?;
+
+        tokens.push(ParserToken::Jump(start_label));
+        tokens.push(ParserToken::Label(end_label));
+
+        Ok(tokens)
+    }
+
+    /**
+     * "return;" or "return expr;". The expression, if any, is evaluated onto the stack before
+     * the `Ret()` token so the stack machine finds the return value sitting on top.
+     */
+    fn return_statement(&mut self) -> Result<Vec<ParserToken>, ParseError> {
+        let mut tokens = vec![];
+
+        // eat "return" keyword
+        self.eat();
+
+        if !self.peek_is(&LexerToken::Symbol(';')) {
+            tokens.append(&mut self.parse_expr(vec![LexerToken::Symbol(';')])?);
         }
-        panic!("expected an identifier after 'fn' keyword");
+        self.eat_expect(LexerToken::Symbol(';'))?;
+
+        tokens.push(ParserToken::Ret());
+        Ok(tokens)
     }
 
-    #[must_use]
-    fn function_call(&mut self, fn_name: String) -> Vec<ParserToken> {
-        // TODO: Parse Arguments
-        self.eat_expect(LexerToken::Operator(")".to_string()));
-        self.eat_expect(LexerToken::Symbol(';'));
-        return vec![ParserToken::Call(fn_name, 0)];
+    /**
+     * "struct Name { field0, field1, ... }", fields separated by commas or semicolons, with an
+     * optional trailing separator before the closing "}".
+     */
+    fn struct_decleration(&mut self) -> Result<Vec<ParserToken>, ParseError> {
+        // eat "struct" keyword
+        self.eat();
+
+        self.track_expected(Expected::Identifier);
+        let expected = self.expected_vec();
+        let tk_identifier = self.eat().ok_or(ParseError::UnexpectedEof { expected: self.expected_vec(), span: self.last_span })?;
+        if let LexerToken::Identifier(struct_name) = tk_identifier {
+            self.eat_expect(LexerToken::Symbol('{'))?;
+
+            let mut fields: Vec<String> = vec![];
+            'fields : loop {
+                if self.peek_is(&LexerToken::Symbol('}')) {
+                    self.eat(); // "}"
+                    break 'fields;
+                }
+
+                self.track_expected(Expected::Identifier);
+                let expected = self.expected_vec();
+                let tk = self.eat().ok_or(ParseError::UnexpectedEof { expected: self.expected_vec(), span: self.last_span })?;
+                if let LexerToken::Identifier(field_name) = tk {
+                    fields.push(field_name);
+                }
+                else {
+                    return Err(ParseError::UnexpectedToken { expected, found: tk, span: self.last_span });
+                }
+
+                self.track_expected(Expected::Token(LexerToken::Symbol(',')));
+                self.track_expected(Expected::Token(LexerToken::Symbol(';')));
+                self.track_expected(Expected::Token(LexerToken::Symbol('}')));
+                let expected = self.expected_vec();
+                let sep = self.eat().ok_or(ParseError::UnexpectedEof { expected: self.expected_vec(), span: self.last_span })?;
+                if sep == LexerToken::Symbol('}') {
+                    break 'fields;
+                }
+                else if sep != LexerToken::Symbol(',') && sep != LexerToken::Symbol(';') {
+                    return Err(ParseError::UnexpectedToken { expected, found: sep, span: self.last_span });
+                }
+            }
+
+            return Ok(vec![ParserToken::DeclareStruct(struct_name, fields)]);
+        }
+        Err(ParseError::UnexpectedToken { expected, found: tk_identifier, span: self.last_span })
     }
 
     /**
-     * Terminator is used to determine when the expression is suppost to end, terminator doesn't get eaten. e.g: 
+     * Parses "{ field0: expr, ... }" construction syntax, "{" has already been eaten by the
+     * caller. Shares the commalist shape with `function_call`.
+     */
+    fn construct_struct(&mut self, struct_name: String) -> Result<Vec<ParserToken>, ParseError> {
+        let mut tokens = vec![];
+        let mut field_count: u8 = 0;
+        let terminator = vec![LexerToken::Symbol(','), LexerToken::Symbol('}')];
+
+        if !self.peek_is(&LexerToken::Symbol('}')) {
+            'fields : loop {
+                self.track_expected(Expected::Identifier);
+                let expected = self.expected_vec();
+                let tk_field = self.eat().ok_or(ParseError::UnexpectedEof { expected: self.expected_vec(), span: self.last_span })?;
+                if !matches!(tk_field, LexerToken::Identifier(_)) {
+                    return Err(ParseError::UnexpectedToken { expected, found: tk_field, span: self.last_span });
+                }
+                self.eat_expect(LexerToken::Symbol(':'))?;
+
+                tokens.append(&mut self.parse_expr(terminator.clone())?);
+                field_count += 1;
+
+                let sep = self.eat_expect_any(&terminator)?;
+                if sep == LexerToken::Symbol('}') {
+                    break 'fields;
+                }
+            }
+        }
+        else {
+            self.eat(); // "}"
+        }
+
+        tokens.push(ParserToken::ConstructStruct(struct_name, field_count));
+        Ok(tokens)
+    }
+
+    /**
+     * "ident.field", reachable both as a bare statement (from `parse_until`) and as an
+     * expression value (from `parse_expr`, e.g. "let x = point.y;"): evaluates the base
+     * identifier with `GetVariable`, then pops it and pushes the named field with `GetField`.
+     */
+    fn field_access(&mut self, base: String, base_span: Span) -> Result<Vec<ParserToken>, ParseError> {
+        let mut tokens = vec![ParserToken::GetVariable(base, base_span)];
+
+        self.track_expected(Expected::Identifier);
+        let expected = self.expected_vec();
+        let tk_field = self.eat().ok_or(ParseError::UnexpectedEof { expected: self.expected_vec(), span: self.last_span })?;
+        if let LexerToken::Identifier(field_name) = tk_field {
+            tokens.push(ParserToken::GetField(field_name));
+            return Ok(tokens);
+        }
+        Err(ParseError::UnexpectedToken { expected, found: tk_field, span: self.last_span })
+    }
+
+    /**
+     * Parses a comma-separated argument list, "(" has already been eaten by the caller.
+     * Mirrors the usual "commalist" shape: peek for the closing ")" first so a zero-arg
+     * call doesn't try to parse an expression, otherwise parse-item / expect-comma-or-close.
+     * Each argument goes through `parse_expr`, so a nested call like "foo(bar(1), 2)" produces
+     * a correctly-aritied inner `Call` whether this call itself was reached from statement
+     * position or from another `parse_expr` call.
+     */
+    fn function_call(&mut self, fn_name: String, span: Span) -> Result<Vec<ParserToken>, ParseError> {
+        let mut tokens = vec![];
+        let mut arg_count: u8 = 0;
+        let terminator = vec![LexerToken::Symbol(','), LexerToken::Operator(")".to_string())];
+
+        if !self.peek_is(&LexerToken::Operator(")".to_string())) {
+            'args : loop {
+                tokens.append(&mut self.parse_expr(terminator.clone())?);
+                arg_count += 1;
+
+                let sep = self.eat_expect_any(&terminator)?;
+                if sep == LexerToken::Operator(")".to_string()) {
+                    break 'args;
+                }
+            }
+        }
+        else {
+            self.eat(); // ")"
+        }
+
+        tokens.push(ParserToken::Call(fn_name, arg_count, span));
+        Ok(tokens)
+    }
+
+    /**
+     * Parses a single expression value wherever one is expected - a `let` initializer, an
+     * `if`/`while` condition, a `return` value, a call argument, a struct field value. If the
+     * next tokens are `identifier (` / `identifier {` / `identifier .`, they're handed straight
+     * to `function_call`/`construct_struct`/`field_access` so those forms produce a correctly
+     * structured `Call`/`ConstructStruct`/`GetField` even in expression position - notably a
+     * nested call like "foo(bar(1), 2)" round-trips its inner arity correctly, since the
+     * argument loop in `function_call` calls back into `parse_expr` for each argument, and
+     * likewise a struct field value can itself be a call or another struct construction.
+     * Anything else (arithmetic, literals, ...) falls back to the raw `eat_expr` +
+     * `AstExpr::evaluate` path, which lives outside this file.
+     */
+    fn parse_expr(&mut self, terminator: Vec<LexerToken>) -> Result<Vec<ParserToken>, ParseError> {
+        if let Some(LexerToken::Identifier(name)) = self.peek().cloned() {
+            let span = self.peek_span();
+            match self.peek_at(1) {
+                Some(LexerToken::Operator(op)) if op == "(" => {
+                    self.eat(); // identifier
+                    self.eat(); // "("
+                    return self.function_call(name, span);
+                },
+                Some(LexerToken::Symbol('{')) => {
+                    self.eat(); // identifier
+                    self.eat(); // "{"
+                    return self.construct_struct(name);
+                },
+                Some(LexerToken::Symbol('.')) => {
+                    self.eat(); // identifier
+                    self.eat(); // "."
+                    return self.field_access(name, span);
+                },
+                _ => {},
+            }
+        }
+
+        let mut expr = self.eat_expr(terminator)?;
+        Ok(AstExpr::evaluate(&mut expr))
+    }
+
+    /**
+     * Terminator is used to determine when the expression is suppost to end, terminator doesn't get eaten. e.g:
      * "LexerToken::Symbol(';')" for "let x = 2+2;"
      * "LexerToken::Symbol(',')" for "fn foo(2+2+2, 0)"
      * "LexerToken::Operator(')')" for "fn foo(2+2+2)" // this is going to be a fucking problem, lol.
+     *
+     * `paren_depth` stops this raw token collection from truncating at a nested call's own ")",
+     * for callers (like `parse_expr`'s fallback) that don't already know the expression is a call.
      */
-    fn eat_expr(&mut self, terminator: Vec<LexerToken>) -> VecDeque<LexerToken> {
+    fn eat_expr(&mut self, terminator: Vec<LexerToken>) -> Result<VecDeque<LexerToken>, ParseError> {
         let mut out_tks = VecDeque::new();
+        let mut paren_depth: i32 = 0;
         'get_tokens: loop {
+            for tk in &terminator {
+                self.track_expected(Expected::Token(tk.clone()));
+            }
             let peeked = self.peek();
             if peeked.is_none() {
-                panic!("Expected '{:?}' got EOF instead!", terminator);
+                return Err(ParseError::UnexpectedEof { expected: self.expected_vec(), span: self.last_span });
             }
-            if terminator.contains(peeked.unwrap()) {
+            // Only treat the terminator as a stop token while we're not nested inside a
+            // call's own parentheses, so "foo(bar(1), 2)" doesn't stop at the inner ")".
+            if paren_depth == 0 && terminator.contains(peeked.unwrap()) {
                 break 'get_tokens;
             }
 
             let token = self.eat().unwrap();
+            match &token {
+                LexerToken::Operator(op) if op == "(" => paren_depth += 1,
+                LexerToken::Operator(op) if op == ")" => paren_depth -= 1,
+                _ => {}
+            }
             out_tks.push_back(token);
         }
-        return out_tks;
+        Ok(out_tks)
     }
 
     fn peek(&self) -> Option<&LexerToken> {
-        if self.input.len() == 0 {
+        if self.input.is_empty() {
             return None;
         }
-        self.input.front()
+        self.input.front().map(|spanned| &spanned.value)
     }
 
-    fn eat_checked(&mut self) -> LexerToken {
-        let popped = self.eat();
-        if popped.is_none() {
-            panic!("Got unexpected EOF");
+    /**
+     * Like `peek`, but looks `offset` tokens ahead without consuming anything. Used to tell
+     * a plain identifier expression apart from one headed into a call/struct-construction/
+     * field-access before committing to either path.
+     */
+    fn peek_at(&self, offset: usize) -> Option<&LexerToken> {
+        self.input.get(offset).map(|spanned| &spanned.value)
+    }
+
+    /**
+     * The span of the next token, or the span of the last consumed token if we're at EOF.
+     */
+    fn peek_span(&self) -> Span {
+        self.input.front().map(|spanned| spanned.span).unwrap_or(self.last_span)
+    }
+
+    /**
+     * Peeks for a specific keyword (e.g. "else" following an "if" body) without consuming it.
+     */
+    fn peek_is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(LexerToken::Keyword(kw)) if kw == keyword)
+    }
+
+    /**
+     * Allocates a fresh, monotonically increasing label id used by `JumpIfFalse`/`Jump` until
+     * `backpatch` resolves it to a real instruction index.
+     */
+    fn next_label(&mut self) -> usize {
+        let label = self.label_counter;
+        self.label_counter += 1;
+        label
+    }
+
+    /**
+     * Resolves every `Label` marker in `tokens` to the instruction index it ends up at once the
+     * labels themselves are stripped out, then rewrites every `Jump`/`JumpIfFalse` target from a
+     * label id to that resolved index. Run once per independent instruction stream (a function
+     * body or the top-level program), since label ids are otherwise meaningless offsets.
+     */
+    fn backpatch(tokens: Vec<ParserToken>) -> Vec<ParserToken> {
+        let mut positions = std::collections::HashMap::new();
+        let mut resolved = Vec::with_capacity(tokens.len());
+        for token in tokens {
+            if let ParserToken::Label(label) = token {
+                positions.insert(label, resolved.len());
+            }
+            else {
+                resolved.push(token);
+            }
+        }
+
+        for token in resolved.iter_mut() {
+            match token {
+                ParserToken::Jump(label) => *token = ParserToken::Jump(*positions.get(label).expect("Jump to an unresolved label")),
+                ParserToken::JumpIfFalse(label) => *token = ParserToken::JumpIfFalse(*positions.get(label).expect("JumpIfFalse to an unresolved label")),
+                _ => {}
+            }
         }
-        popped.unwrap()
+
+        resolved
+    }
+
+    /**
+     * Peeks the next token and records `tok` as a token the parser was willing to accept here,
+     * so a later error can report it among the "expected" set even if this particular check
+     * doesn't end up being the one that fails.
+     */
+    fn peek_is(&mut self, tok: &LexerToken) -> bool {
+        self.track_expected(Expected::Token(tok.clone()));
+        self.peek() == Some(tok)
     }
 
-    fn eat_expect(&mut self, expect: LexerToken) -> LexerToken {
-        let popped = self.eat();
-        if popped.is_none() {
-            panic!("Expected {:?} got EOF instead!", expect);
+    fn eat_expect(&mut self, expect: LexerToken) -> Result<LexerToken, ParseError> {
+        self.track_expected(Expected::Token(expect.clone()));
+        match self.peek() {
+            None => Err(ParseError::UnexpectedEof { expected: self.expected_vec(), span: self.last_span }),
+            Some(tk) if tk == &expect => Ok(self.eat().unwrap()),
+            Some(found) => {
+                let found = found.clone();
+                let span = self.peek_span();
+                Err(ParseError::UnexpectedToken { expected: self.expected_vec(), found, span })
+            },
         }
-        let tk = popped.unwrap();
-        if tk != expect {
-            panic!("Expected {:?} got {:?} instead! :(", expect, tk);
+    }
+
+    /**
+     * Like `eat_expect`, but accepts any token out of a set of alternatives (e.g. "," or ")").
+     */
+    fn eat_expect_any(&mut self, expect: &[LexerToken]) -> Result<LexerToken, ParseError> {
+        for tk in expect {
+            self.track_expected(Expected::Token(tk.clone()));
+        }
+        match self.peek() {
+            None => Err(ParseError::UnexpectedEof { expected: self.expected_vec(), span: self.last_span }),
+            Some(tk) if expect.contains(tk) => Ok(self.eat().unwrap()),
+            Some(found) => {
+                let found = found.clone();
+                let span = self.peek_span();
+                Err(ParseError::UnexpectedToken { expected: self.expected_vec(), found, span })
+            },
         }
-        tk
+    }
+
+    /**
+     * Registers `exp` as something the parser would have accepted at the current position, in
+     * the order it was checked. Deduplicated so checking the same token from multiple call sites
+     * (e.g. `peek_is` immediately followed by an `eat_expect` on the same token) doesn't repeat
+     * it in the rendered "expected one of ..." message.
+     */
+    fn track_expected(&mut self, exp: Expected) {
+        if !self.expected.contains(&exp) {
+            self.expected.push(exp);
+        }
+    }
+
+    fn expected_vec(&self) -> Vec<Expected> {
+        self.expected.clone()
     }
 
     fn eat(&mut self) -> Option<LexerToken> {
-        self.input.pop_front()
+        let spanned = self.input.pop_front()?;
+        self.expected.clear();
+        self.last_span = spanned.span;
+        Some(spanned.value)
     }
 
-    fn new(tks: VecDeque<LexerToken>) -> Parser { 
+    fn new(tks: VecDeque<Spanned<LexerToken>>) -> Parser {
         Parser {
             input: tks,
+            expected: Vec::new(),
+            label_counter: 0,
+            last_span: Span::new(0, 0),
         }
     }
 }
@@ -0,0 +1,31 @@
+/**
+ * A half-open byte range `[start, end)` into the original source, used to point diagnostics at
+ * the token that produced them.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+}
+
+/**
+ * Wraps a lexer token with the span it was lexed from, so later stages (the parser, and
+ * eventually error reporting) don't have to re-derive positions from scratch.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<T> {
+    pub value: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(value: T, span: Span) -> Spanned<T> {
+        Spanned { value, span }
+    }
+}